@@ -0,0 +1,63 @@
+use crate::ray::Ray;
+use crate::vec3::Point3;
+
+/// An axis-aligned bounding box, used by `BvhNode` to skip whole subtrees
+/// of objects a ray can't possibly hit.
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+
+        for axis in 0..3 {
+            let origin = component(ray.origin, axis);
+            let dir = component(ray.dir, axis);
+            let min = component(self.min, axis);
+            let max = component(self.max, axis);
+
+            let inv_d = 1.0 / dir;
+            let mut t0 = (min - origin) * inv_d;
+            let mut t1 = (max - origin) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// The smallest box enclosing both `box0` and `box1`.
+pub fn surrounding_box(box0: Aabb, box1: Aabb) -> Aabb {
+    Aabb {
+        min: Point3 {
+            x: f64::min(box0.min.x, box1.min.x),
+            y: f64::min(box0.min.y, box1.min.y),
+            z: f64::min(box0.min.z, box1.min.z),
+        },
+        max: Point3 {
+            x: f64::max(box0.max.x, box1.max.x),
+            y: f64::max(box0.max.y, box1.max.y),
+            z: f64::max(box0.max.z, box1.max.z),
+        },
+    }
+}
+
+pub fn component(v: Point3, axis: usize) -> f64 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
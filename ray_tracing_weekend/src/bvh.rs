@@ -0,0 +1,137 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use crate::aabb::{component, surrounding_box, Aabb};
+use crate::ray::Ray;
+use crate::vec3::Point3;
+use crate::{HitRecord, Hittable};
+
+/// A bounding volume hierarchy over a list of hittables. Splitting the list
+/// in half at each level, round-robining the split axis (x, then y, then z,
+/// repeating per depth), keeps `hit` close to O(log n) instead of an O(n)
+/// linear scan over every object.
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+/// A hittable with no geometry: `hit` always misses. Used as the BVH's
+/// children when `BvhNode::new` is given an empty object list, so an empty
+/// scene is never hit (and does not panic) instead of `build` recursing
+/// forever on an ever-empty slice.
+struct EmptyHittable;
+
+impl Hittable for EmptyHittable {
+    fn hit(&self, _ray: Ray, _t_min: f64, _t_max: f64) -> Option<HitRecord> {
+        None
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        None
+    }
+}
+
+impl BvhNode {
+    pub fn new(objects: Vec<Arc<dyn Hittable>>, time0: f64, time1: f64) -> BvhNode {
+        if objects.is_empty() {
+            let empty: Arc<dyn Hittable> = Arc::new(EmptyHittable);
+            return BvhNode {
+                left: Arc::clone(&empty),
+                right: empty,
+                bbox: Aabb {
+                    min: Point3::default(),
+                    max: Point3::default(),
+                },
+            };
+        }
+
+        let mut objects = objects;
+        match Self::build(&mut objects, 0, time0, time1) {
+            BuiltNode::Node(node) => node,
+            BuiltNode::Leaf(leaf) => BvhNode {
+                bbox: leaf
+                    .bounding_box(time0, time1)
+                    .expect("hittable with no bounding box in BVH"),
+                left: Arc::clone(&leaf),
+                right: leaf,
+            },
+        }
+    }
+
+    fn build(objects: &mut [Arc<dyn Hittable>], axis: usize, time0: f64, time1: f64) -> BuiltNode {
+        let span = objects.len();
+
+        if span == 1 {
+            return BuiltNode::Leaf(objects[0].clone());
+        }
+
+        objects.sort_by(|a, b| box_centroid_cmp(a.as_ref(), b.as_ref(), axis, time0, time1));
+
+        if span == 2 {
+            let left = objects[0].clone();
+            let right = objects[1].clone();
+            let bbox = surrounding_box(
+                left.bounding_box(time0, time1).expect("missing bounding box"),
+                right.bounding_box(time0, time1).expect("missing bounding box"),
+            );
+            return BuiltNode::Node(BvhNode { left, right, bbox });
+        }
+
+        let mid = span / 2;
+        let next_axis = (axis + 1) % 3;
+        let left = Self::build(&mut objects[..mid], next_axis, time0, time1).into_hittable();
+        let right = Self::build(&mut objects[mid..], next_axis, time0, time1).into_hittable();
+
+        let bbox = surrounding_box(
+            left.bounding_box(time0, time1).expect("missing bounding box"),
+            right.bounding_box(time0, time1).expect("missing bounding box"),
+        );
+
+        BuiltNode::Node(BvhNode { left, right, bbox })
+    }
+}
+
+enum BuiltNode {
+    Leaf(Arc<dyn Hittable>),
+    Node(BvhNode),
+}
+
+impl BuiltNode {
+    fn into_hittable(self) -> Arc<dyn Hittable> {
+        match self {
+            BuiltNode::Leaf(leaf) => leaf,
+            BuiltNode::Node(node) => Arc::new(node),
+        }
+    }
+}
+
+fn box_centroid_cmp(a: &dyn Hittable, b: &dyn Hittable, axis: usize, time0: f64, time1: f64) -> Ordering {
+    let box_a = a.bounding_box(time0, time1).expect("missing bounding box");
+    let box_b = b.bounding_box(time0, time1).expect("missing bounding box");
+
+    let centroid_a = (component(box_a.min, axis) + component(box_a.max, axis)) / 2.0;
+    let centroid_b = (component(box_b.min, axis) + component(box_b.max, axis)) / 2.0;
+
+    centroid_a
+        .partial_cmp(&centroid_b)
+        .unwrap_or(Ordering::Equal)
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return None;
+        }
+
+        let hit_left = self.left.hit(ray, t_min, t_max);
+        let closest_so_far = hit_left.as_ref().map_or(t_max, |record| record.t);
+        let hit_right = self.right.hit(ray, t_min, closest_so_far);
+
+        hit_right.or(hit_left)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
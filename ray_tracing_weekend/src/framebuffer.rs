@@ -0,0 +1,60 @@
+use image::{ImageResult, Rgba, RgbaImage};
+
+use crate::math_util::clamp;
+use crate::vec3::Colour;
+
+/// Accumulates per-pixel colour samples for the whole image so the render
+/// loop and the final encode step are decoupled: workers fill in rows, and
+/// only at the end do we tone-map and hand the result to the `image` crate.
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Colour>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Colour::default(); width * height],
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, colour: Colour) {
+        self.pixels[y * self.width + x] = colour;
+    }
+
+    /// Gamma-corrects and averages the accumulated samples into 8-bit RGBA.
+    fn to_rgba_image(&self, samples_per_pixel: i32) -> RgbaImage {
+        let scale = 1.0 / samples_per_pixel as f64;
+        let mut image = RgbaImage::new(self.width as u32, self.height as u32);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let c = self.pixels[y * self.width + x];
+                let r = f64::sqrt(scale * c.x);
+                let g = f64::sqrt(scale * c.y);
+                let b = f64::sqrt(scale * c.z);
+
+                image.put_pixel(
+                    x as u32,
+                    y as u32,
+                    Rgba([
+                        (256.0 * clamp(r, 0.0, 0.999)) as u8,
+                        (256.0 * clamp(g, 0.0, 0.999)) as u8,
+                        (256.0 * clamp(b, 0.0, 0.999)) as u8,
+                        255,
+                    ]),
+                );
+            }
+        }
+
+        image
+    }
+
+    /// Encodes and writes the image, picking the format from `path`'s extension.
+    pub fn save(&self, path: &str, samples_per_pixel: i32) -> ImageResult<()> {
+        self.to_rgba_image(samples_per_pixel).save(path)
+    }
+}
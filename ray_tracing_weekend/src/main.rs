@@ -1,4 +1,8 @@
-use std::{f64::consts::PI, rc::Rc};
+use std::{
+    f64::consts::PI,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
 
 mod vec3;
 use vec3::*;
@@ -9,8 +13,20 @@ use math_util::*;
 mod ray;
 use ray::*;
 
-trait Material {
-    fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Colour, Ray)>;
+mod rng;
+use rng::Pcg32;
+
+mod framebuffer;
+use framebuffer::Framebuffer;
+
+mod aabb;
+use aabb::{surrounding_box, Aabb};
+
+mod bvh;
+use bvh::BvhNode;
+
+trait Material: Send + Sync {
+    fn scatter(&self, rng: &mut Pcg32, r_in: Ray, rec: HitRecord) -> Option<(Colour, Ray)>;
 }
 
 struct Lambertian {
@@ -18,11 +34,12 @@ struct Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Colour, Ray)> {
-        let scatter_direction = rec.normal + random_unit_vec3();
+    fn scatter(&self, rng: &mut Pcg32, r_in: Ray, rec: HitRecord) -> Option<(Colour, Ray)> {
+        let scatter_direction = rec.normal + random_unit_vec3(rng);
         let scattered = Ray {
             origin: rec.p,
             dir: scatter_direction,
+            time: r_in.time,
         };
         Some((self.albedo, scattered))
     }
@@ -34,11 +51,12 @@ struct Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Colour, Ray)> {
+    fn scatter(&self, rng: &mut Pcg32, r_in: Ray, rec: HitRecord) -> Option<(Colour, Ray)> {
         let reflected = reflect(r_in.dir.get_normalized(), rec.normal);
         let scattered = Ray {
             origin: rec.p,
-            dir: reflected + self.fuzz * random_vec3_in_unit_sphere(),
+            dir: reflected + self.fuzz * random_vec3_in_unit_sphere(rng),
+            time: r_in.time,
         };
         let attenuation = self.albedo;
 
@@ -55,7 +73,7 @@ struct Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Colour, Ray)> {
+    fn scatter(&self, rng: &mut Pcg32, r_in: Ray, rec: HitRecord) -> Option<(Colour, Ray)> {
         let attenuation = Colour {
             x: 1.0,
             y: 1.0,
@@ -79,17 +97,19 @@ impl Material for Dielectric {
                 Ray {
                     origin: rec.p,
                     dir: reflected,
+                    time: r_in.time,
                 },
             ))
         } else {
             let reflect_prob = schlick(cos_theta, etai_over_etat);
-            if f64::random() < reflect_prob {
+            if f64::random(rng) < reflect_prob {
                 let reflected = reflect(unit_direction, rec.normal);
                 Some((
                     attenuation,
                     Ray {
                         origin: rec.p,
                         dir: reflected,
+                        time: r_in.time,
                     },
                 ))
             } else {
@@ -99,6 +119,7 @@ impl Material for Dielectric {
                     Ray {
                         origin: rec.p,
                         dir: refracted,
+                        time: r_in.time,
                     },
                 ))
             }
@@ -112,7 +133,7 @@ struct HitRecord {
     normal: Vec3,
     t: f64,
     front_face: bool,
-    material: Rc<dyn Material>,
+    material: Arc<dyn Material>,
 }
 
 impl Default for HitRecord {
@@ -122,7 +143,7 @@ impl Default for HitRecord {
             normal: Vec3::default(),
             t: f64::default(),
             front_face: false,
-            material: Rc::new(Lambertian {
+            material: Arc::new(Lambertian {
                 albedo: Vec3::default(),
             }),
         }
@@ -140,14 +161,15 @@ impl HitRecord {
     }
 }
 
-trait Hittable {
+trait Hittable: Send + Sync {
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
 }
 
 struct Sphere {
     center: Point3,
     radius: f64,
-    material: Rc<dyn Material>,
+    material: Arc<dyn Material>,
 }
 
 impl Hittable for Sphere {
@@ -165,7 +187,7 @@ impl Hittable for Sphere {
                 let mut record = HitRecord::default();
                 record.t = temp;
                 record.p = ray.at(record.t);
-                record.material = Rc::clone(&self.material);
+                record.material = Arc::clone(&self.material);
                 let outward_normal = (record.p - self.center) / self.radius;
                 record.set_face_normal(ray, outward_normal);
                 return Some(record);
@@ -175,7 +197,7 @@ impl Hittable for Sphere {
                 let mut record = HitRecord::default();
                 record.t = temp;
                 record.p = ray.at(record.t);
-                record.material = Rc::clone(&self.material);
+                record.material = Arc::clone(&self.material);
                 let outward_normal = (record.p - self.center) / self.radius;
                 record.set_face_normal(ray, outward_normal);
                 return Some(record);
@@ -183,37 +205,97 @@ impl Hittable for Sphere {
         }
         return None;
     }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        let radius = Vec3 {
+            x: self.radius.abs(),
+            y: self.radius.abs(),
+            z: self.radius.abs(),
+        };
+        Some(Aabb {
+            min: self.center - radius,
+            max: self.center + radius,
+        })
+    }
+}
+
+struct MovingSphere {
+    center0: Point3,
+    center1: Point3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    material: Arc<dyn Material>,
 }
 
-struct HittableList {
-    objects: Vec<Rc<dyn Hittable>>,
+impl MovingSphere {
+    fn center(&self, time: f64) -> Point3 {
+        self.center0
+            + ((time - self.time0) / (self.time1 - self.time0)) * (self.center1 - self.center0)
+    }
 }
 
-impl Hittable for HittableList {
+impl Hittable for MovingSphere {
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut record = HitRecord::default();
-        let mut hit_anithing = false;
-        let mut closest_so_far = t_max;
-
-        self.objects
-            .iter()
-            .for_each(|object| match object.hit(ray, t_min, closest_so_far) {
-                Some(object_hit_record) => {
-                    record = object_hit_record.clone();
-                    hit_anithing = true;
-                    closest_so_far = object_hit_record.t;
-                }
-                None => {}
-            });
+        let center = self.center(ray.time);
+        let oc = ray.origin - center;
+        let a = ray.dir.length_squared();
+        let half_b = dot(oc, ray.dir);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
 
-        if hit_anithing {
-            Some(record)
-        } else {
-            None
+        if discriminant > 0.0 {
+            let root = f64::sqrt(discriminant);
+            let temp = (-half_b - root) / a;
+            if temp < t_max && temp > t_min {
+                let mut record = HitRecord::default();
+                record.t = temp;
+                record.p = ray.at(record.t);
+                record.material = Arc::clone(&self.material);
+                let outward_normal = (record.p - center) / self.radius;
+                record.set_face_normal(ray, outward_normal);
+                return Some(record);
+            }
+            let temp = (-half_b + root) / a;
+            if temp < t_max && temp > t_min {
+                let mut record = HitRecord::default();
+                record.t = temp;
+                record.p = ray.at(record.t);
+                record.material = Arc::clone(&self.material);
+                let outward_normal = (record.p - center) / self.radius;
+                record.set_face_normal(ray, outward_normal);
+                return Some(record);
+            }
         }
+        return None;
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let radius = Vec3 {
+            x: self.radius.abs(),
+            y: self.radius.abs(),
+            z: self.radius.abs(),
+        };
+        let box0 = Aabb {
+            min: self.center(time0) - radius,
+            max: self.center(time0) + radius,
+        };
+        let box1 = Aabb {
+            min: self.center(time1) - radius,
+            max: self.center(time1) + radius,
+        };
+        Some(surrounding_box(box0, box1))
     }
 }
 
+/// The interval `[open, close]` during which the camera's shutter is open,
+/// sampled uniformly per ray to produce motion blur.
+#[derive(Clone, Copy, Debug)]
+struct Shutter {
+    open: f64,
+    close: f64,
+}
+
 #[derive(Clone, Copy, Debug)]
 struct Camera {
     origin: Point3,
@@ -221,6 +303,7 @@ struct Camera {
     horizontal: Vec3,
     vertical: Vec3,
     lens_radius: f64,
+    shutter: Shutter,
 }
 
 impl Default for Camera {
@@ -248,6 +331,7 @@ impl Default for Camera {
             },
             lower_left_corner: Vec3::default(),
             lens_radius: 1.0,
+            shutter: Shutter { open: 0.0, close: 0.0 },
         };
         camera.lower_left_corner = camera.origin
             - camera.horizontal / 2.0
@@ -270,6 +354,28 @@ fn create_camera(
     aspect_ratio: f64,
     aperture: f64,
     focus_dist: f64,
+) -> Camera {
+    create_camera_with_shutter(
+        lookfrom,
+        lookat,
+        vup,
+        vfov,
+        aspect_ratio,
+        aperture,
+        focus_dist,
+        Shutter { open: 0.0, close: 0.0 },
+    )
+}
+
+fn create_camera_with_shutter(
+    lookfrom: Point3,
+    lookat: Point3,
+    vup: Vec3,
+    vfov: f64,
+    aspect_ratio: f64,
+    aperture: f64,
+    focus_dist: f64,
+    shutter: Shutter,
 ) -> Camera {
     let theta = f64::to_radians(vfov);
     let h = f64::tan(theta / 2.0);
@@ -293,19 +399,21 @@ fn create_camera(
         horizontal: horizontal,
         vertical: vertical,
         lens_radius: lens_radius,
+        shutter: shutter,
     }
 }
 
-fn get_ray(camera: Camera, s: f64, t: f64) -> Ray {
-    let rd = camera.lens_radius * random_in_unit_disk();
+fn get_ray(rng: &mut Pcg32, camera: Camera, s: f64, t: f64) -> Ray {
+    let rd = camera.lens_radius * random_in_unit_disk(rng);
     let offset = Vec3{x: s * rd.x, y: t * rd.y, z: 0.0};
     Ray {
         origin: camera.origin + offset,
         dir: camera.lower_left_corner + s * camera.horizontal + t * camera.vertical - camera.origin - offset,
+        time: f64::random_range(rng, camera.shutter.open, camera.shutter.close),
     }
 }
 
-fn ray_colour(ray: Ray, hittable: Box<&dyn Hittable>, depth: i32) -> Colour {
+fn ray_colour(rng: &mut Pcg32, ray: Ray, hittable: Box<&dyn Hittable>, depth: i32) -> Colour {
     if depth <= 0 {
         return Colour {
             x: 0.0,
@@ -315,9 +423,9 @@ fn ray_colour(ray: Ray, hittable: Box<&dyn Hittable>, depth: i32) -> Colour {
     }
 
     match hittable.hit(ray, 0.001, infinite()) {
-        Some(record) => match record.material.scatter(ray, record.clone()) {
+        Some(record) => match record.material.scatter(rng, ray, record.clone()) {
             Some((attenuation, scattered)) => {
-                return attenuation * ray_colour(scattered, hittable, depth - 1);
+                return attenuation * ray_colour(rng, scattered, hittable, depth - 1);
             }
             None => {
                 return Colour::default();
@@ -341,24 +449,6 @@ fn ray_colour(ray: Ray, hittable: Box<&dyn Hittable>, depth: i32) -> Colour {
         }
 }
 
-fn write_colour(pixel_colour: Colour, samples_per_pixel: i32) {
-    let mut r = pixel_colour.x;
-    let mut g = pixel_colour.y;
-    let mut b = pixel_colour.z;
-
-    let scale = 1.0 / samples_per_pixel as f64;
-    r = f64::sqrt(scale * r);
-    g = f64::sqrt(scale * g);
-    b = f64::sqrt(scale * b);
-
-    println!(
-        "{} {} {}",
-        (255.999 * r) as i32,
-        (255.999 * g) as i32,
-        (255.999 * b) as i32
-    );
-}
-
 fn main() {
     let aspect_ratio = 16.0 / 9.0;
     let image_width = 384;
@@ -367,25 +457,38 @@ fn main() {
     let samples_per_pixel = 100;
     let depth = 50;
 
-    println!("P3");
-    println!("{0} {1}", image_width, image_height);
-    println!("255");
+    let output_path = std::env::args().nth(1).unwrap_or_else(|| "output.png".to_string());
 
     let lookfrom = Point3{x: -2.0, y: 2.0, z: 1.0};
     let lookat =  Point3{x: 0.0, y:0.0, z:-1.0};
     let vup = Vec3{x: 0.0, y: 1.0, z: 0.0};
-    let camera = create_camera(lookfrom, lookat, vup, 20.0, image_width as f64 / image_height as f64, 2.0, (lookfrom - lookat).length());
+    let camera = create_camera_with_shutter(
+        lookfrom,
+        lookat,
+        vup,
+        20.0,
+        image_width as f64 / image_height as f64,
+        2.0,
+        (lookfrom - lookat).length(),
+        Shutter { open: 0.0, close: 1.0 },
+    );
 
-    let world = HittableList {
-        objects: vec![
-            Rc::new(Sphere {
-                center: Point3 {
+    let world_objects: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(MovingSphere {
+                center0: Point3 {
                     x: 0.0,
                     y: 0.0,
                     z: -1.0,
                 },
+                center1: Point3 {
+                    x: 0.0,
+                    y: 0.3,
+                    z: -1.0,
+                },
+                time0: camera.shutter.open,
+                time1: camera.shutter.close,
                 radius: 0.5,
-                material: Rc::new(Lambertian {
+                material: Arc::new(Lambertian {
                     albedo: Colour {
                         x: 0.1,
                         y: 0.2,
@@ -393,14 +496,14 @@ fn main() {
                     },
                 }),
             }),
-            Rc::new(Sphere {
+            Arc::new(Sphere {
                 center: Point3 {
                     x: 0.0,
                     y: -100.5,
                     z: -1.0,
                 },
                 radius: 100.0,
-                material: Rc::new(Lambertian {
+                material: Arc::new(Lambertian {
                     albedo: Colour {
                         x: 0.8,
                         y: 0.8,
@@ -408,14 +511,14 @@ fn main() {
                     },
                 }),
             }),
-            Rc::new(Sphere {
+            Arc::new(Sphere {
                 center: Point3 {
                     x: 1.0,
                     y: 0.0,
                     z: -1.0,
                 },
                 radius: 0.5,
-                material: Rc::new(Metal {
+                material: Arc::new(Metal {
                     albedo: Colour {
                         x: 0.8,
                         y: 0.6,
@@ -424,43 +527,164 @@ fn main() {
                     fuzz: 0.3
                 }),
             }),
-            Rc::new(Sphere {
+            Arc::new(Sphere {
                 center: Point3 {
                     x: -1.0,
                     y: 0.0,
                     z: -1.0,
                 },
                 radius: 0.5,
-                material: Rc::new(Dielectric{ref_idx: 1.5}),
+                material: Arc::new(Dielectric{ref_idx: 1.5}),
             }),
-            Rc::new(Sphere {
+            Arc::new(Sphere {
                 center: Point3 {
                     x: -1.0,
                     y: 0.0,
                     z: -1.0,
                 },
                 radius: -0.45,
-                material: Rc::new(Dielectric{ref_idx: 1.5}),
+                material: Arc::new(Dielectric{ref_idx: 1.5}),
             }),
-        ],
-    };
+        ];
+
+    let world = BvhNode::new(world_objects, camera.shutter.open, camera.shutter.close);
 
     let bar = indicatif::ProgressBar::new(image_height as u64);
 
-    for j in (0..image_height).rev() {
-        for i in 0..image_width {
-            let mut pixel_colour: Colour = Colour::default();
-            for _s in 0..samples_per_pixel {
-                let u = (i as f64 + f64::random()) / (image_width - 1) as f64;
-                let v = (j as f64 + f64::random()) / (image_height - 1) as f64;
-
-                let r = get_ray(camera, u, v);
-                pixel_colour += ray_colour(r, Box::new(&world), depth);
+    let seed: u64 = std::env::var("RTW_SEED")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+
+    let world = Arc::new(world);
+    let num_workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+
+    let (job_tx, job_rx) = mpsc::channel::<i32>();
+    let job_rx = Arc::new(Mutex::new(job_rx));
+    let (result_tx, result_rx) = mpsc::channel::<(i32, Vec<Colour>)>();
+
+    for _ in 0..num_workers {
+        let job_rx = Arc::clone(&job_rx);
+        let result_tx = result_tx.clone();
+        let world = Arc::clone(&world);
+
+        thread::spawn(move || loop {
+            let j = match job_rx.lock().unwrap().recv() {
+                Ok(j) => j,
+                Err(_) => break,
+            };
+
+            // Re-seed per row from (seed, j) rather than keeping one
+            // persistent RNG per worker: which worker ends up rendering
+            // row `j` depends on scheduling, so a worker-keyed stream
+            // would make row `j`'s pixels depend on that scheduling too.
+            // Keying on `j` itself makes every row's samples reproducible
+            // regardless of how rows are distributed across workers.
+            let mut rng = Pcg32::new(seed, j as u64);
+
+            let mut row = Vec::with_capacity(image_width as usize);
+            for i in 0..image_width {
+                let mut pixel_colour: Colour = Colour::default();
+                for _s in 0..samples_per_pixel {
+                    let u = (i as f64 + f64::random(&mut rng)) / (image_width - 1) as f64;
+                    let v = (j as f64 + f64::random(&mut rng)) / (image_height - 1) as f64;
+
+                    let r = get_ray(&mut rng, camera, u, v);
+                    pixel_colour += ray_colour(&mut rng, r, Box::new(world.as_ref()), depth);
+                }
+                row.push(pixel_colour);
             }
-            write_colour(pixel_colour, samples_per_pixel);
-        }
 
+            if result_tx.send((j, row)).is_err() {
+                break;
+            }
+        });
+    }
+    drop(result_tx);
+
+    for j in (0..image_height).rev() {
+        job_tx.send(j).unwrap();
+    }
+    drop(job_tx);
+
+    let mut rows: Vec<Option<Vec<Colour>>> = (0..image_height).map(|_| None).collect();
+    for _ in 0..image_height {
+        let (j, row) = result_rx.recv().unwrap();
+        rows[j as usize] = Some(row);
         bar.inc(1);
     }
+
+    let mut framebuffer = Framebuffer::new(image_width as usize, image_height as usize);
+    for j in 0..image_height {
+        // `j` counts scanlines bottom-up (as the serial renderer did), but
+        // image row 0 is the top of the picture, so flip it here.
+        let image_row = (image_height - 1 - j) as usize;
+        for (i, pixel_colour) in rows[j as usize].take().unwrap().into_iter().enumerate() {
+            framebuffer.set(i, image_row, pixel_colour);
+        }
+    }
+
+    framebuffer
+        .save(&output_path, samples_per_pixel)
+        .expect("failed to encode output image");
     eprintln!("Done.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moving_sphere_center_interpolates_linearly() {
+        let sphere = MovingSphere {
+            center0: Point3 { x: 0.0, y: 0.0, z: 0.0 },
+            center1: Point3 { x: 0.0, y: 1.0, z: 0.0 },
+            time0: 0.0,
+            time1: 1.0,
+            radius: 0.5,
+            material: Arc::new(Lambertian { albedo: Colour::default() }),
+        };
+
+        let mid = sphere.center(0.5);
+        assert!((mid.y - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn moving_sphere_hits_at_its_interpolated_center() {
+        let sphere = MovingSphere {
+            center0: Point3 { x: 0.0, y: 0.0, z: -1.0 },
+            center1: Point3 { x: 0.0, y: 1.0, z: -1.0 },
+            time0: 0.0,
+            time1: 1.0,
+            radius: 0.5,
+            material: Arc::new(Lambertian { albedo: Colour::default() }),
+        };
+        let ray = Ray {
+            origin: Point3 { x: 0.0, y: 1.0, z: 0.0 },
+            dir: Vec3 { x: 0.0, y: 0.0, z: -1.0 },
+            time: 1.0,
+        };
+
+        let hit = sphere.hit(ray, 0.001, infinite());
+        assert!(hit.is_some());
+        assert!((hit.unwrap().t - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn create_camera_defaults_to_a_still_camera() {
+        let camera = create_camera(
+            Point3 { x: 0.0, y: 0.0, z: 0.0 },
+            Point3 { x: 0.0, y: 0.0, z: -1.0 },
+            Vec3 { x: 0.0, y: 1.0, z: 0.0 },
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+
+        assert_eq!(camera.shutter.open, 0.0);
+        assert_eq!(camera.shutter.close, 0.0);
+    }
+}
@@ -1,16 +1,18 @@
+use crate::rng::Pcg32;
+
 pub trait Random {
     type Output;
-    fn random() -> Self::Output;
-    fn random_range(min: f64, max: f64) -> Self::Output;
+    fn random(rng: &mut Pcg32) -> Self::Output;
+    fn random_range(rng: &mut Pcg32, min: f64, max: f64) -> Self::Output;
 }
 
 impl Random for f64 {
     type Output = f64;
-    fn random() -> Self::Output {
-        rand::random()
+    fn random(rng: &mut Pcg32) -> Self::Output {
+        rng.next_f64()
     }
-    fn random_range(min: f64, max: f64) -> Self::Output {
-        min + (max - min) * Self::random()
+    fn random_range(rng: &mut Pcg32, min: f64, max: f64) -> Self::Output {
+        min + (max - min) * Self::random(rng)
     }
 }
 
@@ -4,6 +4,7 @@ use crate::vec3::*;
 pub struct Ray {
     pub origin: Point3,
     pub dir: Vec3,
+    pub time: f64,
 }
 
 impl Ray {
@@ -0,0 +1,35 @@
+// A small PCG32 (XSH-RR, 64-bit state / 32-bit output) implementation, after
+// O'Neill's reference algorithm. Self-contained so renders are reproducible
+// from a seed without depending on any particular `rand` crate version.
+pub struct Pcg32 {
+    state: u64,
+    inc: u64,
+}
+
+const PCG_DEFAULT_MULTIPLIER: u64 = 6364136223846793005;
+
+impl Pcg32 {
+    pub fn new(seed: u64, sequence: u64) -> Self {
+        let mut rng = Pcg32 { state: 0, inc: (sequence << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state
+            .wrapping_mul(PCG_DEFAULT_MULTIPLIER)
+            .wrapping_add(self.inc);
+
+        let xorshifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rot = (old_state >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Uniform f64 in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u32() as f64) / (u32::MAX as f64 + 1.0)
+    }
+}
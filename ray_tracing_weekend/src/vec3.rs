@@ -1,6 +1,7 @@
 use std::{f64::consts::PI, ops};
 
 use crate::math_util::{self, Random};
+use crate::rng::Pcg32;
 
 #[derive(Copy, Debug, Clone)]
 pub struct Vec3 {
@@ -146,6 +147,15 @@ pub fn reflect(v: Vec3, normal: Vec3) -> Vec3 {
     v - 2.0 * dot(v, normal) * normal
 }
 
+/// Snell's law: bends a unit incident direction `uv` through a surface with
+/// normal `n` given the ratio of refractive indices `etai_over_etat`.
+pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
+    let cos_theta = dot(-uv, n);
+    let r_out_parallel = etai_over_etat * (uv + cos_theta * n);
+    let r_out_perp = -f64::sqrt(1.0 - r_out_parallel.length_squared()) * n;
+    r_out_parallel + r_out_perp
+}
+
 impl Vec3 {
     pub fn length_squared(self) -> f64 {
         self.x * self.x + self.y * self.y + self.z * self.z
@@ -166,36 +176,29 @@ impl Vec3 {
 
 impl math_util::Random for Vec3 {
     type Output = Vec3;
-    fn random() -> Vec3 {
+    fn random(rng: &mut Pcg32) -> Vec3 {
         Vec3 {
-            x: f64::random(),
-            y: f64::random(),
-            z: f64::random(),
+            x: f64::random(rng),
+            y: f64::random(rng),
+            z: f64::random(rng),
         }
     }
-    fn random_range(min: f64, max: f64) -> Vec3 {
+    fn random_range(rng: &mut Pcg32, min: f64, max: f64) -> Vec3 {
         Vec3 {
-            x: f64::random_range(min, max),
-            y: f64::random_range(min, max),
-            z: f64::random_range(min, max),
-        }
-    }
-}
-
-pub fn random_vec3_in_unit_sphere() -> Vec3 {
-    loop {
-        let p = Vec3::random_range(-1.0, 1.0);
-        if p.length_squared() > 1.0 {
-            continue;
-        } else {
-            return p;
+            x: f64::random_range(rng, min, max),
+            y: f64::random_range(rng, min, max),
+            z: f64::random_range(rng, min, max),
         }
     }
 }
 
-pub fn random_unit_vec3() -> Vec3 {
-    let a = f64::random_range(0.0, 2.0 * PI);
-    let z = f64::random_range(-1.0, 1.0);
+/// A uniformly distributed point on the unit sphere, sampled directly
+/// (no rejection loop): draw `z` in `[-1, 1]` and an angle `a` in `[0, 2π]`,
+/// then place the point on the circle of radius `r = sqrt(1 - z^2)` at
+/// height `z`.
+pub fn random_unit_vec3(rng: &mut Pcg32) -> Vec3 {
+    let a = f64::random_range(rng, 0.0, 2.0 * PI);
+    let z = f64::random_range(rng, -1.0, 1.0);
     let r = f64::sqrt(1.0 - z * z);
     Vec3 {
         x: r * f64::cos(a),
@@ -204,8 +207,29 @@ pub fn random_unit_vec3() -> Vec3 {
     }
 }
 
-pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-    let in_unit_sphere = random_vec3_in_unit_sphere();
+/// A uniformly distributed point *inside* the unit ball (volume, not just
+/// its surface), still rejection-free: a unit-sphere direction scaled by a
+/// radius drawn so that density stays uniform by volume (`cbrt` of a
+/// uniform `[0, 1)` sample, since volume grows with the cube of radius).
+pub fn random_vec3_in_unit_sphere(rng: &mut Pcg32) -> Vec3 {
+    random_unit_vec3(rng) * f64::cbrt(f64::random(rng))
+}
+
+/// A uniformly distributed point in the unit disk, sampled directly:
+/// draw `r = sqrt(u1)` and `theta = 2*pi*u2` so area density stays uniform
+/// (a naive `(r, theta)` both uniform would bunch samples near the centre).
+pub fn random_in_unit_disk(rng: &mut Pcg32) -> Vec3 {
+    let r = f64::sqrt(f64::random(rng));
+    let theta = f64::random_range(rng, 0.0, 2.0 * PI);
+    Vec3 {
+        x: r * f64::cos(theta),
+        y: r * f64::sin(theta),
+        z: 0.0,
+    }
+}
+
+pub fn random_in_hemisphere(rng: &mut Pcg32, normal: Vec3) -> Vec3 {
+    let in_unit_sphere = random_vec3_in_unit_sphere(rng);
     if dot(in_unit_sphere, normal) > 0.0 {
         in_unit_sphere
     } else {